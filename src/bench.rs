@@ -0,0 +1,28 @@
+//! Building the `#[bench]` items for `bench` subblocks.
+
+use syntax::ast::{self, Ident};
+use syntax::ext::base::ExtCtxt;
+use syntax::ptr::P;
+
+/// Build the `#[bench] fn <sanitized description>(bencher: &mut test::Bencher)`
+/// item for a single `bench` subblock.
+///
+/// `before`/`after`, already flattened by `generate` to include every
+/// `before_each`/`after_each` inherited from the enclosing `describe!`
+/// and its ancestors, are spliced in around the user's body. They run
+/// outside of `bencher.iter(..)`, so only the expression the user
+/// explicitly passes to `iter` is timed; setup and teardown run once per
+/// bench fn invocation, not once per iteration.
+pub fn generate_bench(cx: &mut ExtCtxt, description: &str, bencher: Ident, body: &P<ast::Block>,
+                       before: &[P<ast::Stmt>], after: &[P<ast::Stmt>]) -> P<ast::Item> {
+    let fn_name = cx.ident_of(&::describe::sanitize_ident(description));
+
+    quote_item!(cx,
+        #[bench]
+        fn $fn_name($bencher: &mut ::test::Bencher) {
+            $before
+            $body
+            $after
+        }
+    ).unwrap()
+}
@@ -0,0 +1,32 @@
+//! The `describe!` syntax extension's entry point: ties `parse` and
+//! `generate` together and registers the resulting item with the
+//! compiler.
+
+use syntax::ast::{self, Ident, TokenTree};
+use syntax::codemap::Span;
+use syntax::ext::base::{ExtCtxt, MacResult, MacEager};
+use syntax::parse::parser::Parser;
+
+use generate::generate;
+use parse::parse;
+
+/// Replace every character that is not valid in the middle of a Rust
+/// identifier with `_`, so free-form test descriptions can be turned into
+/// function names.
+pub fn sanitize_ident(description: &str) -> String {
+    description.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// `IdentTT` handler registered for `describe!` in `plugin_registrar`.
+///
+/// Parses the block named by `ident` with `parse::parse`, then lowers it
+/// with `generate::generate` into the `mod` item that replaces the macro
+/// invocation.
+pub fn describe(cx: &mut ExtCtxt, sp: Span, ident: Ident, tts: Vec<TokenTree>) -> Box<MacResult + 'static> {
+    let mut parser = cx.new_parser_from_tts(&tts);
+    let desc = parse(cx, sp, ident, &mut parser);
+    let item = generate(cx, sp, &desc);
+    MacEager::items(::syntax::util::small_vector::SmallVector::one(item))
+}
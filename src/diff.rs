@@ -0,0 +1,97 @@
+//! The reference LCS line diff behind the diff-rendering
+//! `assert_eq!`/`assert_ne!` replacement that `generate` silently injects
+//! into every generated `describe!` block. The injected code carries its
+//! own inlined copy of this algorithm and does not call this module.
+
+use std::env;
+use std::fmt::Debug;
+use std::io::IsTerminal;
+
+/// An edit between two sequences of lines, as produced by `diff_lines`.
+enum Edit<'a> {
+    /// A line present, unchanged, in both operands.
+    Equal(&'a str),
+    /// A line only present in the left (`expected`/first) operand.
+    Delete(&'a str),
+    /// A line only present in the right (`actual`/second) operand.
+    Insert(&'a str),
+}
+
+/// Render `{:#?}` of `left` and `right`, diff them line-by-line, and
+/// return a human-readable block: common lines printed plain, left-only
+/// lines prefixed with `-`, right-only lines prefixed with `+`. ANSI
+/// color is only used when stdout is a TTY.
+///
+/// `generate::diff_prelude` injects an inlined copy of this function into
+/// every `describe!` block's prelude; the generated code does not call
+/// this function itself. Not part of the public API.
+#[doc(hidden)]
+pub fn render_diff<T: Debug>(left: &T, right: &T) -> String {
+    let left_str = format!("{:#?}", left);
+    let right_str = format!("{:#?}", right);
+    let left_lines: Vec<&str> = left_str.lines().collect();
+    let right_lines: Vec<&str> = right_str.lines().collect();
+
+    let edits = diff_lines(&left_lines, &right_lines);
+    let color = use_color();
+
+    let mut out = String::new();
+    for edit in edits {
+        let (prefix, text, code) = match edit {
+            Edit::Equal(line) => (" ", line, None),
+            Edit::Delete(line) => ("-", line, Some("31")),
+            Edit::Insert(line) => ("+", line, Some("32")),
+        };
+        match (color, code) {
+            (true, Some(code)) => out.push_str(&format!("\x1b[{}m{}{}\x1b[0m\n", code, prefix, text)),
+            _ => out.push_str(&format!("{}{}\n", prefix, text)),
+        }
+    }
+    out
+}
+
+fn use_color() -> bool {
+    env::var_os("NO_COLOR").is_none() && ::std::io::stdout().is_terminal()
+}
+
+/// Longest-common-subsequence line diff: standard LCS DP table over the
+/// two line vectors, then backtrack to a sequence of `Equal`/`Delete`/
+/// `Insert` edits.
+fn diff_lines<'a>(left: &[&'a str], right: &[&'a str]) -> Vec<Edit<'a>> {
+    let (n, m) = (left.len(), right.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if left[i] == right[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if left[i] == right[j] {
+            edits.push(Edit::Equal(left[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            edits.push(Edit::Delete(left[i]));
+            i += 1;
+        } else {
+            edits.push(Edit::Insert(right[j]));
+            j += 1;
+        }
+    }
+    while i < n {
+        edits.push(Edit::Delete(left[i]));
+        i += 1;
+    }
+    while j < m {
+        edits.push(Edit::Insert(right[j]));
+        j += 1;
+    }
+    edits
+}
@@ -0,0 +1,369 @@
+//! Lowering a parsed `describe!` block (`parse::Desc`) into the real
+//! `mod`/`fn` items that get spliced into the user's source.
+
+use syntax::ast::{self, Ident};
+use syntax::codemap::Span;
+use syntax::ext::base::ExtCtxt;
+use syntax::parse::token;
+use syntax::ptr::P;
+
+use parse::{Desc, Subblock};
+use test;
+use bench;
+
+/// A `before_all` binding that survived `extract_before_all_bindings`:
+/// its name and the explicit type it was declared with, which `generate`
+/// uses to give its storage static a concrete, nameable type.
+pub type BeforeAllBinding = (Ident, P<ast::Ty>);
+
+/// Generate the `mod <name> { .. }` item for a parsed `describe!` block,
+/// inlining `before_each`/`after_each` statements inherited from this
+/// block and every block it's nested inside into every test, and
+/// recursing into nested `describe!` blocks.
+///
+/// A `runner <Type>;` directive on `desc` (the `custom_test_frameworks`
+/// `Testable` type) applies to this block and is inherited by every block
+/// nested inside it; a nested block's own `runner` overrides it for that
+/// block and every block nested inside it.
+pub fn generate(cx: &mut ExtCtxt, sp: Span, desc: &Desc) -> P<ast::Item> {
+    generate_with_runner(cx, sp, desc, None, &[], &[])
+}
+
+/// `ancestor_before`/`ancestor_after` are the already-accumulated
+/// `before_each`/`after_each` statements of every enclosing `describe!`,
+/// outermost first; they're prepended (`before`) or appended (`after`) to
+/// `desc`'s own, so nested blocks see their ancestors' setup/teardown
+/// exactly as if it were copy-pasted into them, outer setup running
+/// before inner setup and inner teardown running before outer teardown.
+fn generate_with_runner(cx: &mut ExtCtxt, sp: Span, desc: &Desc, inherited_runner: Option<Ident>,
+                         ancestor_before: &[P<ast::Stmt>], ancestor_after: &[P<ast::Stmt>]) -> P<ast::Item> {
+    let name = desc.name;
+    let runner = desc.runner.or(inherited_runner);
+    let mut items = Vec::new();
+
+    let before: Vec<P<ast::Stmt>> =
+        ancestor_before.iter().cloned().chain(desc.before_each.iter().cloned()).collect();
+    let after: Vec<P<ast::Stmt>> =
+        desc.after_each.iter().cloned().chain(ancestor_after.iter().cloned()).collect();
+
+    // `use` items declared directly inside this block come first, ahead
+    // of the `pub use super::*;` prelude, so a name imported here shadows
+    // (rather than conflicts with) one re-exported from the parent.
+    items.extend(desc.uses.iter().cloned());
+    items.push(quote_item!(cx, pub use super::*;).unwrap());
+    items.extend(diff_prelude(cx));
+
+    let mut before_all_bindings: Vec<BeforeAllBinding> = Vec::new();
+    if desc.before_all.is_some() || desc.after_all.is_some() {
+        let (guard_items, bindings) = generate_once_guard(cx, desc);
+        items.extend(guard_items);
+        before_all_bindings = bindings;
+    }
+
+    for subblock in desc.subblocks.iter() {
+        match *subblock {
+            Subblock::It(ref description, ref body) => {
+                items.push(test::generate_test(cx, desc, description, body, test::Kind::Normal,
+                                                runner, &before_all_bindings, &before, &after));
+            }
+            Subblock::Failing(ref description, ref msg, ref body) => {
+                items.push(test::generate_test(cx, desc, description, body,
+                                                test::Kind::Failing(msg.clone()), runner,
+                                                &before_all_bindings, &before, &after));
+            }
+            Subblock::Ignore(ref description, ref body) => {
+                items.push(test::generate_test(cx, desc, description, body, test::Kind::Ignored,
+                                                runner, &before_all_bindings, &before, &after));
+            }
+            Subblock::Bench(ref description, bencher, ref body) => {
+                items.push(bench::generate_bench(cx, description, bencher, body, &before, &after));
+            }
+            Subblock::ItForEach(ref description, binding, ref cases, ref body) => {
+                items.extend(test::generate_test_cases(cx, desc, description, binding, cases, body,
+                                                         runner, &before_all_bindings, &before, &after));
+            }
+            Subblock::Describe(ref inner) => {
+                items.push(generate_with_runner(cx, sp, inner, runner, &before, &after));
+            }
+        }
+    }
+
+    quote_item!(cx, mod $name { $items }).unwrap()
+}
+
+/// Shadow `assert_eq!`/`assert_ne!` for the duration of this block with
+/// versions that append a `{:#?}`-based, LCS line-diff to the panic
+/// message on failure, so `it` bodies that use plain `assert_eq!` get
+/// readable failures without any change to the test itself. The original
+/// `left == right`/`left != right` summary line is kept first so
+/// `failing` blocks matching on that text still work; the diff is
+/// appended after it. Both the plain two-operand form and the
+/// `assert_eq!(left, right, "msg {}", arg)` custom-message form are
+/// shadowed, so existing tests using either keep compiling.
+///
+/// The generated `mod` lives in the *user's* crate, which only has
+/// `describe!` available via `#![plugin(stainless)]` and never an
+/// `extern crate stainless;` — there is no path back to this plugin
+/// crate that the generated code could call through. So the rendering
+/// helper (`__stainless_render_diff`, a copy of `::diff::render_diff`'s
+/// LCS algorithm) is inlined as a free fn in the same prelude as the
+/// macros that call it, rather than referenced by path.
+fn diff_prelude(cx: &mut ExtCtxt) -> Vec<P<ast::Item>> {
+    vec![
+        quote_item!(cx,
+            fn __stainless_render_diff<T: ::std::fmt::Debug>(left: &T, right: &T) -> String {
+                let left_str = format!("{:#?}", left);
+                let right_str = format!("{:#?}", right);
+                let left_lines: Vec<&str> = left_str.lines().collect();
+                let right_lines: Vec<&str> = right_str.lines().collect();
+
+                let (n, m) = (left_lines.len(), right_lines.len());
+                let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+                for i in (0..n).rev() {
+                    for j in (0..m).rev() {
+                        lcs[i][j] = if left_lines[i] == right_lines[j] {
+                            lcs[i + 1][j + 1] + 1
+                        } else {
+                            lcs[i + 1][j].max(lcs[i][j + 1])
+                        };
+                    }
+                }
+
+                let color = ::std::env::var_os("NO_COLOR").is_none()
+                    && ::std::io::IsTerminal::is_terminal(&::std::io::stdout());
+                let mut out = String::new();
+                let (mut i, mut j) = (0, 0);
+                while i < n && j < m {
+                    if left_lines[i] == right_lines[j] {
+                        out.push_str(&format!(" {}\n", left_lines[i]));
+                        i += 1;
+                        j += 1;
+                    } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+                        if color {
+                            out.push_str(&format!("\x1b[31m-{}\x1b[0m\n", left_lines[i]));
+                        } else {
+                            out.push_str(&format!("-{}\n", left_lines[i]));
+                        }
+                        i += 1;
+                    } else {
+                        if color {
+                            out.push_str(&format!("\x1b[32m+{}\x1b[0m\n", right_lines[j]));
+                        } else {
+                            out.push_str(&format!("+{}\n", right_lines[j]));
+                        }
+                        j += 1;
+                    }
+                }
+                while i < n {
+                    if color {
+                        out.push_str(&format!("\x1b[31m-{}\x1b[0m\n", left_lines[i]));
+                    } else {
+                        out.push_str(&format!("-{}\n", left_lines[i]));
+                    }
+                    i += 1;
+                }
+                while j < m {
+                    if color {
+                        out.push_str(&format!("\x1b[32m+{}\x1b[0m\n", right_lines[j]));
+                    } else {
+                        out.push_str(&format!("+{}\n", right_lines[j]));
+                    }
+                    j += 1;
+                }
+                out
+            }
+        ).unwrap(),
+        quote_item!(cx,
+            macro_rules! assert_eq {
+                ($left:expr, $right:expr) => ({
+                    match (&$left, &$right) {
+                        (left_val, right_val) => {
+                            if !(*left_val == *right_val) {
+                                panic!("assertion failed: `(left == right)`\n{}",
+                                       __stainless_render_diff(left_val, right_val));
+                            }
+                        }
+                    }
+                });
+                ($left:expr, $right:expr, $($arg:tt)+) => ({
+                    match (&$left, &$right) {
+                        (left_val, right_val) => {
+                            if !(*left_val == *right_val) {
+                                panic!("assertion failed: `(left == right)`\n{}\n{}",
+                                       __stainless_render_diff(left_val, right_val),
+                                       format_args!($($arg)+));
+                            }
+                        }
+                    }
+                });
+            }
+        ).unwrap(),
+        quote_item!(cx,
+            macro_rules! assert_ne {
+                ($left:expr, $right:expr) => ({
+                    match (&$left, &$right) {
+                        (left_val, right_val) => {
+                            if *left_val == *right_val {
+                                panic!("assertion failed: `(left != right)`\n{}",
+                                       __stainless_render_diff(left_val, right_val));
+                            }
+                        }
+                    }
+                });
+                ($left:expr, $right:expr, $($arg:tt)+) => ({
+                    match (&$left, &$right) {
+                        (left_val, right_val) => {
+                            if *left_val == *right_val {
+                                panic!("assertion failed: `(left != right)`\n{}\n{}",
+                                       __stainless_render_diff(left_val, right_val),
+                                       format_args!($($arg)+));
+                            }
+                        }
+                    }
+                });
+            }
+        ).unwrap(),
+    ]
+}
+
+/// Emit the `static INIT: ::std::sync::Once` plus everything it guards:
+/// one `static mut __STAINLESS_BEFORE_ALL_<name>: Option<Ty>` per
+/// `before_all` binding (so the value outlives the single call that
+/// produces it and is visible to every test's local scope, the same way
+/// `static mut` + `Once` was used to share one-time-computed values
+/// before cells like `OnceLock` existed), and the `__stainless_once_init`
+/// fn that populates them and registers `after_all`. The
+/// `INIT.call_once(__stainless_once_init)` that every generated test
+/// makes on entry is spliced in by `test::build_test_item`.
+///
+/// Returns the bindings so callers can splice a
+/// `let <name> = unsafe { __STAINLESS_BEFORE_ALL_<name>.as_ref().unwrap() };`
+/// into every generated test, exposing `before_all`'s bindings to test
+/// bodies as shared references — `before_all` runs once and is shared
+/// across every test (possibly running on independent threads), so
+/// unlike `before_each`'s fresh owned bindings, these can't be handed
+/// out as mutable owned values.
+///
+/// `before_all` bindings must carry an explicit type annotation (e.g.
+/// `let shared: Vec<i32> = ...;`) — `static` items need a concrete,
+/// spelled-out type, and unlike `before_each` (whose statements are
+/// simply re-run, so the compiler infers their types per test as usual)
+/// the `before_all` value has to be nameable once, up front.
+///
+/// `after_all` has no reliable single "last test" hook under a harness
+/// that may run tests in parallel on independent threads, so instead
+/// its body is registered, from inside the same one-time init that runs
+/// `before_all`, with the C runtime's `atexit`: it runs once, at process
+/// exit, and is documented as such rather than guaranteed to run
+/// immediately after any particular test.
+fn generate_once_guard(cx: &mut ExtCtxt, desc: &Desc) -> (Vec<P<ast::Item>>, Vec<BeforeAllBinding>) {
+    let mut out = Vec::new();
+    out.push(quote_item!(cx, static INIT: ::std::sync::Once = ::std::sync::Once::new();).unwrap());
+
+    let bindings = match desc.before_all {
+        Some(ref before) => extract_before_all_bindings(cx, before),
+        None => Vec::new(),
+    };
+
+    for &(ident, ref ty) in bindings.iter() {
+        let storage = before_all_storage_ident(ident);
+        out.push(quote_item!(cx,
+            static mut $storage: ::std::option::Option<$ty> = ::std::option::Option::None;
+        ).unwrap());
+    }
+
+    if desc.after_all.is_some() {
+        out.push(quote_item!(cx,
+            extern "C" { fn atexit(callback: extern "C" fn()) -> i32; }
+        ).unwrap());
+    }
+
+    let init_body = desc.before_all.as_ref().map(|before| rewrite_before_all_body(cx, before));
+    let register_after_all = desc.after_all.as_ref().map(|_| {
+        quote_stmt!(cx, unsafe { atexit(__stainless_run_after_all); }).unwrap()
+    });
+
+    out.push(quote_item!(cx,
+        fn __stainless_once_init() {
+            $init_body
+            $register_after_all
+        }
+    ).unwrap());
+
+    if let Some(ref after) = desc.after_all {
+        let after_all_lets: Vec<_> = bindings.iter().map(|&(name, _)| {
+            let storage = before_all_storage_ident(name);
+            quote_stmt!(cx, let $name = unsafe { $storage.as_ref().unwrap() };).unwrap()
+        }).collect();
+        out.push(quote_item!(cx,
+            extern "C" fn __stainless_run_after_all() {
+                $after_all_lets
+                $after
+            }
+        ).unwrap());
+    }
+
+    (out, bindings)
+}
+
+/// Pull every top-level `let <ident>: <Ty> = <expr>;` out of a
+/// `before_all` block. Anything else in the block (other statements,
+/// including side-effecting setup calls) is left in place by
+/// `rewrite_before_all_body` and simply runs once, in order, same as
+/// always; it just isn't exposed as a binding.
+fn extract_before_all_bindings(cx: &mut ExtCtxt, block: &P<ast::Block>) -> Vec<BeforeAllBinding> {
+    let mut bindings = Vec::new();
+    for stmt in block.stmts.iter() {
+        if let ast::StmtKind::Local(ref local) = stmt.node {
+            if let ast::PatKind::Ident(_, ref spanned_ident, None) = local.pat.node {
+                match local.ty {
+                    Some(ref ty) if local.init.is_some() =>
+                        bindings.push((spanned_ident.node, ty.clone())),
+                    Some(_) => {}
+                    None => cx.span_err(stmt.span,
+                        "`before_all` bindings need an explicit type, e.g. \
+                         `let shared: Vec<i32> = ...;`, so stainless can share them \
+                         behind a process-wide `static`"),
+                }
+            }
+        }
+    }
+    bindings
+}
+
+/// Rewrite a `before_all` block so that each top-level typed `let` found
+/// by `extract_before_all_bindings` stores into its storage static
+/// instead of creating a local binding; every other statement (and the
+/// block's tail expression, if any) is left untouched and still only
+/// runs the one time `__stainless_once_init` does.
+fn rewrite_before_all_body(cx: &mut ExtCtxt, block: &P<ast::Block>) -> P<ast::Block> {
+    let mut new_stmts = Vec::new();
+    for stmt in block.stmts.iter() {
+        let replaced = if let ast::StmtKind::Local(ref local) = stmt.node {
+            match (&local.pat.node, &local.ty, &local.init) {
+                (&ast::PatKind::Ident(_, ref spanned_ident, None), &Some(_), &Some(ref init)) => {
+                    let storage = before_all_storage_ident(spanned_ident.node);
+                    let init_expr = init.clone();
+                    Some(quote_stmt!(cx,
+                        unsafe { $storage = ::std::option::Option::Some($init_expr); }
+                    ).unwrap())
+                }
+                _ => None,
+            }
+        } else {
+            None
+        };
+        new_stmts.push(replaced.unwrap_or_else(|| stmt.clone()));
+    }
+
+    let mut rewritten = (**block).clone();
+    rewritten.stmts = new_stmts;
+    P(rewritten)
+}
+
+/// The name of the `static mut Option<Ty>` that stores a given
+/// `before_all` binding's value, e.g. `shared` becomes
+/// `__STAINLESS_BEFORE_ALL_shared`.
+pub fn before_all_storage_ident(name: Ident) -> Ident {
+    token::str_to_ident(&format!("__STAINLESS_BEFORE_ALL_{}", name))
+}
@@ -55,6 +55,7 @@
 //! Stainless currently supports the following types of subblocks:
 //!
 //! * `before_each` and `after_each`
+//! * `before_all` and `after_all`
 //! * `it`, `failing`, and `ignore`
 //! * `bench`
 //! * nested `describe!`
@@ -63,15 +64,37 @@
 //! initialization and teardown for a group of tests into a single block,
 //! shortening your tests.
 //!
-//! `it` generates tests which use `before_each` and `after_each`.
+//! `before_all` and `after_all` are like `before_each`/`after_each`, but
+//! run only once for the whole `describe!` block instead of once per
+//! test. Use them for expensive shared setup (spinning up a database,
+//! loading a fixture file, building an index) that every test in the
+//! block can safely reuse. Because each generated `it` is an independent
+//! `#[test]` fn that the harness may run on its own thread, `before_all`
+//! is run behind a `std::sync::Once` so it executes exactly once no
+//! matter which test reaches it first. `after_all` has no equivalent
+//! "last test" hook, so it runs best-effort at process exit instead of
+//! being guaranteed to run immediately after the final test.
+//!
+//! Top-level `let` bindings in `before_all` need an explicit type, e.g.
+//! `let shared: Vec<i32> = ...;`, so the value can be stored behind a
+//! `static` and handed to every test as a shared reference; every other
+//! statement in the block just runs once, same as any other setup code.
+//!
+//! `it` generates tests which use `before_each` and `after_each`. A
+//! table-driven variant, `it "description {}" for binding in [case, ..]
+//! { .. }`, runs the same body once per row of the table, with the row
+//! bound to `binding` and its source text interpolated into `{}` to name
+//! the generated test; each row is reported pass/fail independently
+//! instead of the whole table aborting on the first failure.
 //! `failing` does the same, except the generated tests are marked with
 //! `#[should_panic]`. It optionally takes an argument which is matched against the
 //! failure message. `ignore` is equivalent to marking a test with `#[ignore]` which
 //! disables the test by default.
 //!
-//! `bench` allows you to generate benchmarks in the same fashion, though
-//! *`before_each` and `after_each` blocks do not currently affect `bench`
-//! blocks*.
+//! `bench` allows you to generate benchmarks in the same fashion.
+//! `before_each` and `after_each` apply to `bench` blocks just as they do
+//! to `it`, but run outside of the timed `bencher.iter(..)` call, so
+//! setup and teardown aren't counted in the benchmark.
 //!
 //! Nested `describe!` blocks allow you to better organize your tests into
 //! small units and gives you granular control over where `before_each`
@@ -153,18 +176,16 @@
 //!
 //! ## Importing modules
 //!
-//! At this point it is not possible to put `use` statements inside the
-//! `describe!` blocks. To allow usage of data structures from other
-//! modules and crates each `describe!` block comes with a silent `pub use
-//! super::*;` in it. That way everything you `pub use` in the containing
-//! module is available in your tests.
+//! `use` declarations are allowed directly inside a `describe!` block,
+//! anywhere among its `before_each`/subblocks, and are scoped to just
+//! that block and the children nested inside it:
 //!
 //! ```rust
 //! #[cfg(test)]
 //! mod tests {
-//!     pub use std::collections::HashMap;
-//!
 //!     describe! stainless {
+//!         use std::collections::HashMap;
+//!
 //!         it "can use HashMap" {
 //!             let map = HashMap::new();
 //!         }
@@ -172,6 +193,69 @@
 //! }
 //! ```
 //!
+//! Each `describe!` block also comes with a silent `pub use super::*;`,
+//! so everything you `pub use` in the containing module is available in
+//! your tests too, without needing its own `use` line in the block.
+//!
+//! ## Parameterized tests
+//!
+//! ```rust
+//! describe! stainless {
+//!     it "doubles {} correctly" for case in [(1, 2), (2, 4), (3, 6)] {
+//!         let (input, expected) = case;
+//!         assert_eq!(input * 2, expected);
+//!     }
+//! }
+//! ```
+//!
+//! expands to one `#[test]` per row, e.g. `doubles__1__2__correctly`,
+//! `doubles__2__4__correctly`, `doubles__3__6__correctly` (the extra
+//! underscore comes from the pretty-printed tuple's `(1, 2)` rendering
+//! with a space after the comma, which `sanitize_ident` also turns into
+//! `_`).
+//!
+//! ## Diff-based assertion failures
+//!
+//! Every generated `describe!` block silently shadows `assert_eq!` and
+//! `assert_ne!` with versions that, on failure, format both operands
+//! with `{:#?}` and render a line-by-line diff (common lines plain,
+//! removed lines prefixed with `-`, added lines prefixed with `+`,
+//! colored when stdout is a TTY) after the usual `left == right`
+//! summary. No changes to test bodies are needed to get this; it is
+//! purely a nicer panic message for the same assertions you already
+//! write.
+//!
+//! ## Custom test frameworks
+//!
+//! By default, `describe!` lowers `it`/`failing`/`ignore` to plain
+//! `#[test] fn`s and `bench` to `#[bench] fn`s, tying generated tests to
+//! libtest. A `describe!` block (or any block nested inside it) can opt
+//! out of this with a `runner <Type>;` directive as its first line,
+//! naming a `Testable` implementor:
+//!
+//! ```rust,ignore
+//! #![feature(custom_test_frameworks)]
+//! #![test_runner(my_runner)]
+//!
+//! describe! stainless {
+//!     runner MyTestable;
+//!
+//!     it "still reads the same" {
+//!         assert!(true);
+//!     }
+//! }
+//! ```
+//!
+//! Under a `runner` directive, `describe!` emits `#[test_case] const`
+//! items bound to the named type instead of `#[test] fn`s, following the
+//! `custom_test_frameworks` design. This lets `describe!` be used on
+//! targets where the standard harness isn't available (embedded,
+//! `no_std`) or with alternative runners such as property-test harnesses
+//! or golden-file runners. The nested-module structure and
+//! `before_each`/`after_each` inlining stay identical either way. `bench`
+//! blocks are not affected by a `runner` directive and still emit
+//! `#[bench] fn`s.
+//!
 //! ## License
 //!
 //! MIT
@@ -189,6 +273,14 @@ mod test;
 mod bench;
 mod generate;
 
+/// Reference implementation of the LCS line diff used by the
+/// `assert_eq!`/`assert_ne!` replacement `generate` injects into every
+/// `describe!` block. The generated code lives in the *user's* crate and
+/// has no path back to this plugin crate, so `generate::diff_prelude`
+/// inlines its own copy of this algorithm rather than calling here; this
+/// module holds the reference version of that copy.
+pub mod diff;
+
 #[plugin_registrar]
 #[doc(hidden)]
 pub fn plugin_registrar(reg: &mut plugin::Registry) {
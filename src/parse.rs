@@ -0,0 +1,170 @@
+//! Parsing a `describe!` block into the AST that `generate` lowers into
+//! real `mod` and `fn` items.
+
+use syntax::ast::{self, Ident, Stmt};
+use syntax::codemap::Span;
+use syntax::ext::base::ExtCtxt;
+use syntax::parse::parser::Parser;
+use syntax::parse::token;
+use syntax::ptr::P;
+
+/// The parsed representation of a `describe!` block, and (recursively) of
+/// every block nested inside it.
+pub struct Desc {
+    /// The name the block was given; becomes the generated module's name.
+    pub name: Ident,
+    /// `use` items declared directly inside this block (before, after, or
+    /// interleaved with `before_each`/subblocks), emitted ahead of the
+    /// `pub use super::*;` prelude so imports are scoped to just this
+    /// block and the children nested inside it.
+    pub uses: Vec<P<ast::Item>>,
+    /// Statements accumulated from `before_each` blocks in this scope,
+    /// inherited by every `it`/`failing`/`bench` and nested `describe!`.
+    pub before_each: Vec<P<Stmt>>,
+    /// Statements accumulated from `after_each` blocks in this scope.
+    pub after_each: Vec<P<Stmt>>,
+    /// Setup that runs exactly once for the whole block, before the first
+    /// generated test runs.
+    pub before_all: Option<P<ast::Block>>,
+    /// Teardown that runs, best-effort, once after the last generated test.
+    pub after_all: Option<P<ast::Block>>,
+    /// An opt-in `runner <Type>;` directive switching this block (and
+    /// every block nested inside it) from emitting libtest `#[test]`
+    /// items to emitting `#[test_case]` items for
+    /// `#![feature(custom_test_frameworks)]`, bound to the named
+    /// `Testable` implementor. `bench` blocks still emit `#[bench]` items
+    /// under a `runner`. `None` means "use the default harness".
+    pub runner: Option<Ident>,
+    /// The subblocks declared directly inside this block, in source order.
+    pub subblocks: Vec<Subblock>,
+}
+
+/// A single item nested inside a `describe!` block.
+pub enum Subblock {
+    /// `it "description" { .. }`
+    It(String, P<ast::Block>),
+    /// `failing "description" ["message"] { .. }`
+    Failing(String, Option<String>, P<ast::Block>),
+    /// `ignore "description" { .. }`
+    Ignore(String, P<ast::Block>),
+    /// `bench "description" (bencher) { .. }`
+    Bench(String, Ident, P<ast::Block>),
+    /// `it "description {}" for <binding> in [<exprs>, ..] { .. }`: a
+    /// table-driven `it`, generating one test per element with the
+    /// element bound to `binding` and the `{}` in the description
+    /// interpolated with it for that test's function name.
+    ItForEach(String, Ident, Vec<P<ast::Expr>>, P<ast::Block>),
+    /// A nested `describe!` block.
+    Describe(Desc),
+}
+
+/// Parse the contents of a `describe!` block (the token stream following
+/// the block's name) into a `Desc`.
+///
+/// `before_each`/`after_each` may appear anywhere in the block, any number
+/// of times; their statements are accumulated in source order and handed
+/// to `generate` to be spliced into each generated test. `before_all` and
+/// `after_all` may also appear anywhere, but at most once each per block;
+/// each is kept as a single block that `generate` runs once rather than
+/// splicing it into every test.
+pub fn parse(cx: &mut ExtCtxt, sp: Span, name: Ident, parser: &mut Parser) -> Desc {
+    let mut desc = Desc {
+        name: name,
+        uses: Vec::new(),
+        before_each: Vec::new(),
+        after_each: Vec::new(),
+        before_all: None,
+        after_all: None,
+        runner: None,
+        subblocks: Vec::new(),
+    };
+
+    while parser.token != token::Eof {
+        if parser.eat_keyword(token::keywords::Keyword::from_str("runner")) {
+            if !desc.subblocks.is_empty() {
+                cx.span_err(parser.span, "`runner` must appear before any subblocks");
+            }
+            desc.runner = Some(parser.parse_ident().unwrap());
+            parser.expect(&token::Semi);
+        } else if parser.eat_keyword(token::keywords::Keyword::from_str("before_each")) {
+            desc.before_each.extend(parse_block_stmts(cx, parser));
+        } else if parser.eat_keyword(token::keywords::Keyword::from_str("after_each")) {
+            desc.after_each.extend(parse_block_stmts(cx, parser));
+        } else if parser.eat_keyword(token::keywords::Keyword::from_str("before_all")) {
+            if desc.before_all.is_some() {
+                cx.span_err(parser.span, "`before_all` may only appear once per `describe!` block");
+            }
+            desc.before_all = Some(parser.parse_block().unwrap());
+        } else if parser.eat_keyword(token::keywords::Keyword::from_str("after_all")) {
+            if desc.after_all.is_some() {
+                cx.span_err(parser.span, "`after_all` may only appear once per `describe!` block");
+            }
+            desc.after_all = Some(parser.parse_block().unwrap());
+        } else if parser.token.is_keyword(token::keywords::Keyword::from_str("use")) {
+            // Parsed as an ordinary item so paths, globs and `as` renames
+            // all work exactly like `use` anywhere else; only scoped to
+            // this block's generated `mod` rather than the parent module.
+            let item = parser.parse_item().unwrap().unwrap();
+            desc.uses.push(item);
+        } else if parser.eat_keyword(token::keywords::Keyword::from_str("describe")) {
+            let inner_name = parser.parse_ident().unwrap();
+            let inner = parser.parse_block().unwrap();
+            let mut inner_parser = Parser::from_block(inner);
+            let inner_desc = parse(cx, sp, inner_name, &mut inner_parser);
+            desc.subblocks.push(Subblock::Describe(inner_desc));
+        } else {
+            // `it` (including the table-driven `it .. for` form),
+            // `failing`, `ignore` and `bench`; see `parse_subblock` below.
+            desc.subblocks.push(parse_subblock(cx, parser));
+        }
+    }
+
+    desc
+}
+
+fn parse_block_stmts(_cx: &mut ExtCtxt, parser: &mut Parser) -> Vec<P<Stmt>> {
+    let block = parser.parse_block().unwrap();
+    block.stmts.clone()
+}
+
+fn parse_subblock(cx: &mut ExtCtxt, parser: &mut Parser) -> Subblock {
+    if parser.eat_keyword(token::keywords::Keyword::from_str("it")) {
+        let desc_str = parser.parse_str().unwrap();
+        if parser.eat_keyword(token::keywords::Keyword::from_str("for")) {
+            let binding = parser.parse_ident().unwrap();
+            parser.expect_keyword(token::keywords::Keyword::from_str("in"));
+            parser.expect(&token::OpenDelim(token::Bracket));
+            let mut cases = Vec::new();
+            while parser.token != token::CloseDelim(token::Bracket) {
+                cases.push(parser.parse_expr().unwrap());
+                if !parser.eat(&token::Comma) {
+                    break;
+                }
+            }
+            parser.expect(&token::CloseDelim(token::Bracket));
+            let body = parser.parse_block().unwrap();
+            return Subblock::ItForEach(desc_str, binding, cases, body);
+        }
+        let body = parser.parse_block().unwrap();
+        Subblock::It(desc_str, body)
+    } else if parser.eat_keyword(token::keywords::Keyword::from_str("failing")) {
+        let desc_str = parser.parse_str().unwrap();
+        let msg = if parser.token.is_lit() { Some(parser.parse_str().unwrap()) } else { None };
+        let body = parser.parse_block().unwrap();
+        Subblock::Failing(desc_str, msg, body)
+    } else if parser.eat_keyword(token::keywords::Keyword::from_str("ignore")) {
+        let desc_str = parser.parse_str().unwrap();
+        let body = parser.parse_block().unwrap();
+        Subblock::Ignore(desc_str, body)
+    } else if parser.eat_keyword(token::keywords::Keyword::from_str("bench")) {
+        let desc_str = parser.parse_str().unwrap();
+        parser.expect(&token::OpenDelim(token::Paren));
+        let bencher_ident = parser.parse_ident().unwrap();
+        parser.expect(&token::CloseDelim(token::Paren));
+        let body = parser.parse_block().unwrap();
+        Subblock::Bench(desc_str, bencher_ident, body)
+    } else {
+        cx.span_fatal(parser.span, "expected `before_each`, `after_each`, `before_all`, \
+                                     `after_all`, `it`, `failing`, `ignore`, `bench` or `describe`")
+    }
+}
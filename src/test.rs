@@ -0,0 +1,158 @@
+//! Building the `#[test]` items for `it`, `failing` and `ignore` subblocks.
+
+use std::collections::HashSet;
+
+use syntax::ast::{self, Ident};
+use syntax::ext::base::ExtCtxt;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use generate::BeforeAllBinding;
+use parse::Desc;
+
+/// Distinguishes the three flavors of generated test.
+pub enum Kind {
+    /// A plain `it` block, lowered to a bare `#[test]`.
+    Normal,
+    /// A `failing` block, lowered to `#[test] #[should_panic]`, optionally
+    /// with an `expected = "..."` message.
+    Failing(Option<String>),
+    /// An `ignore` block, lowered to `#[test] #[ignore]`.
+    Ignored,
+}
+
+/// Build the `#[test] fn <sanitized description>() { .. }` item for a
+/// single `it`/`failing`/`ignore` subblock, splicing in every
+/// `before_each`/`after_each` statement inherited from the enclosing
+/// `describe!` (and its ancestors) around the user's body.
+///
+/// When `before_all`/`after_all` guards are present on `desc`, the
+/// generated fn calls `INIT.call_once(__stainless_once_init)` before
+/// anything else, so the one-time setup is visible to this test
+/// regardless of which thread the harness schedules it on. Each
+/// `before_all` binding is then re-exposed as a local
+/// `let <name> = unsafe { __STAINLESS_BEFORE_ALL_<name>.as_ref().unwrap() };`
+/// (see `generate::generate_once_guard`), so test bodies can use it just
+/// like a `before_each` binding, as a shared reference.
+///
+/// `runner`, inherited from an enclosing `runner <Type>;` directive,
+/// switches the item shape: instead of a libtest `#[test] fn`, a
+/// `const NAME: &dyn Testable = &...;` is emitted for
+/// `#![feature(custom_test_frameworks)]`, so `describe!` keeps working on
+/// targets (embedded, `no_std`) or with runners where libtest's default
+/// harness isn't available. `failing`/`ignore` are only meaningful under
+/// the default harness, since `Testable` has no standard concept of
+/// "should panic" or "ignored"; using them under a `runner` is a
+/// compile-time error (`cx.span_err`) in the generated code rather than
+/// silently dropped.
+pub fn generate_test(cx: &mut ExtCtxt, desc: &Desc, description: &str, body: &P<ast::Block>,
+                      kind: Kind, runner: Option<Ident>, before_all: &[BeforeAllBinding],
+                      before: &[P<ast::Stmt>], after: &[P<ast::Stmt>]) -> P<ast::Item> {
+    let name_str = ::describe::sanitize_ident(description);
+    build_test_item(cx, desc, &name_str, body, kind, runner, before_all, before, after)
+}
+
+/// Build the `#[test] fn <name>() { .. }` (or `#[test_case] const`, under
+/// a `runner`) item given an already-computed, already-unique function
+/// name. Shared by `generate_test` (which sanitizes `description`
+/// directly) and `generate_test_cases` (which disambiguates one name per
+/// table row before calling in).
+fn build_test_item(cx: &mut ExtCtxt, desc: &Desc, name_str: &str, body: &P<ast::Block>,
+                    kind: Kind, runner: Option<Ident>, before_all: &[BeforeAllBinding],
+                    before: &[P<ast::Stmt>], after: &[P<ast::Stmt>]) -> P<ast::Item> {
+    let call_once = if desc.before_all.is_some() || desc.after_all.is_some() {
+        Some(quote_stmt!(cx, INIT.call_once(__stainless_once_init);).unwrap())
+    } else {
+        None
+    };
+    let before_all_lets: Vec<_> = before_all.iter().map(|&(name, _)| {
+        let storage = ::generate::before_all_storage_ident(name);
+        quote_stmt!(cx, let $name = unsafe { $storage.as_ref().unwrap() };).unwrap()
+    }).collect();
+
+    if let Some(testable) = runner {
+        if let Kind::Failing(_) | Kind::Ignored = kind {
+            cx.span_err(body.span,
+                "`failing`/`ignore` are not supported under a `runner` directive: \
+                 `Testable` has no notion of \"should panic\" or \"ignored\", so this \
+                 case cannot be lowered to a `#[test_case]` item");
+        }
+        let const_name = cx.ident_of(&name_str.to_uppercase());
+        return quote_item!(cx,
+            #[test_case]
+            const $const_name: &'static dyn $testable = &|| {
+                $call_once
+                $before_all_lets
+                $before
+                $body
+                $after
+            };
+        ).unwrap();
+    }
+
+    let fn_name = cx.ident_of(&name_str);
+    let inner = quote_item!(cx,
+        fn $fn_name() {
+            $call_once
+            $before_all_lets
+            $before
+            $body
+            $after
+        }
+    ).unwrap();
+
+    match kind {
+        Kind::Normal => quote_item!(cx, #[test] $inner).unwrap(),
+        Kind::Failing(None) => quote_item!(cx, #[test] #[should_panic] $inner).unwrap(),
+        Kind::Failing(Some(ref msg)) =>
+            quote_item!(cx, #[test] #[should_panic(expected = $msg)] $inner).unwrap(),
+        Kind::Ignored => quote_item!(cx, #[test] #[ignore] $inner).unwrap(),
+    }
+}
+
+/// Build one `#[test] fn` per row of an `it "description {}" for binding
+/// in [case, ..] { .. }` table, each with the row's value bound via a
+/// `let binding = case;` spliced in after the inherited `before_each`
+/// bindings and before the shared body, so the body can close over
+/// `binding` exactly as if it were a plain `it`.
+///
+/// The `{}` in `description` is interpolated, per row, with that row's
+/// source text (its pretty-printed expression); non-identifier
+/// characters in the resulting name are replaced with `_`, and any
+/// collision (e.g. two rows that pretty-print the same) is disambiguated
+/// with a `_<index>` suffix. Each row becomes an independent `#[test]`,
+/// so one failing case is reported on its own instead of aborting the
+/// rest of the table.
+pub fn generate_test_cases(cx: &mut ExtCtxt, desc: &Desc, description: &str, binding: Ident,
+                            cases: &[P<ast::Expr>], body: &P<ast::Block>, runner: Option<Ident>,
+                            before_all: &[BeforeAllBinding], before: &[P<ast::Stmt>],
+                            after: &[P<ast::Stmt>]) -> Vec<P<ast::Item>> {
+    let mut items = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (i, case) in cases.iter().enumerate() {
+        let case_str = pprust::expr_to_string(case);
+        let interpolated = if description.contains("{}") {
+            description.replacen("{}", &case_str, 1)
+        } else {
+            format!("{}_{}", description, case_str)
+        };
+
+        let mut name = ::describe::sanitize_ident(&interpolated);
+        if !seen.insert(name.clone()) {
+            name = format!("{}_{}", name, i);
+            seen.insert(name.clone());
+        }
+
+        let case_expr = case.clone();
+        let case_body = quote_block!(cx, {
+            let $binding = $case_expr;
+            $body
+        }).unwrap();
+
+        items.push(build_test_item(cx, desc, &name, &case_body, Kind::Normal, runner,
+                                    before_all, before, after));
+    }
+
+    items
+}
@@ -0,0 +1,18 @@
+#![feature(plugin,const_fn,test)]
+#![plugin(stainless)]
+
+extern crate test;
+
+describe! stainless {
+    before_each {
+        let base = 2;
+    }
+
+    bench "multiplies using the before_each binding" (bencher) {
+        bencher.iter(|| base * base);
+    }
+
+    after_each {
+        assert_eq!(base, 2);
+    }
+}
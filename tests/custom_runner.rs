@@ -0,0 +1,27 @@
+#![feature(plugin,const_fn,custom_test_frameworks)]
+#![plugin(stainless)]
+#![test_runner(my_runner)]
+
+trait Testable {
+    fn run(&self);
+}
+
+impl<F: Fn()> Testable for F {
+    fn run(&self) {
+        self()
+    }
+}
+
+fn my_runner(tests: &[&dyn Testable]) {
+    for test in tests {
+        test.run();
+    }
+}
+
+describe! stainless {
+    runner Testable;
+
+    it "still works under a custom runner" {
+        assert_eq!(2 + 2, 4);
+    }
+}
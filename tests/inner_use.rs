@@ -0,0 +1,12 @@
+#![feature(plugin,const_fn)]
+#![plugin(stainless)]
+
+describe! stainless {
+    use std::collections::HashMap;
+
+    it "can use a use declared inside the block" {
+        let mut map = HashMap::new();
+        map.insert("a", 1);
+        assert_eq!(map.get("a"), Some(&1));
+    }
+}
@@ -0,0 +1,20 @@
+#![feature(plugin,const_fn)]
+#![plugin(stainless)]
+
+describe! top_level {
+    before_all {
+        let shared: Vec<i32> = vec![1, 2, 3];
+    }
+
+    it "sees the before_all binding" {
+        assert_eq!(*shared, vec![1, 2, 3]);
+    }
+
+    it "sees the same binding from another test" {
+        assert_eq!(shared.len(), 3);
+    }
+
+    after_all {
+        assert_eq!(shared.len(), 3);
+    }
+}
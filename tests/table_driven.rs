@@ -0,0 +1,9 @@
+#![feature(plugin,const_fn)]
+#![plugin(stainless)]
+
+describe! stainless {
+    it "doubles {} correctly" for case in [(1, 2), (2, 4), (3, 6)] {
+        let (input, expected) = case;
+        assert_eq!(input * 2, expected);
+    }
+}